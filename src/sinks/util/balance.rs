@@ -0,0 +1,314 @@
+use crate::sinks::util::adaptive_concurrency::AdaptiveConcurrencyLimit;
+use crate::sinks::util::circuit_breaker::CircuitBreaker;
+use rand::Rng;
+use std::task::{Context, Poll};
+use tower::{limit::RateLimit, retry::Retry, Service};
+
+/// Reports a service's current in-flight load, so that a `Balance` can pick
+/// the least-loaded of two randomly sampled endpoints (power-of-two-choices).
+pub trait Load {
+    fn load(&self) -> usize;
+}
+
+impl<S, L> Load for AdaptiveConcurrencyLimit<S, L> {
+    fn load(&self) -> usize {
+        self.in_flight()
+    }
+}
+
+impl<S: Load> Load for tower::timeout::Timeout<S> {
+    fn load(&self) -> usize {
+        self.get_ref().load()
+    }
+}
+
+impl<P, S: Load> Load for Retry<P, S> {
+    fn load(&self) -> usize {
+        self.get_ref().load()
+    }
+}
+
+impl<S: Load, RL> Load for CircuitBreaker<S, RL> {
+    fn load(&self) -> usize {
+        self.get_ref().load()
+    }
+}
+
+impl<S: Load> Load for RateLimit<S> {
+    fn load(&self) -> usize {
+        self.get_ref().load()
+    }
+}
+
+/// A fixed set of per-endpoint services balanced with power-of-two-choices:
+/// each dispatch samples two endpoints at random and routes to whichever
+/// currently reports the lower in-flight load. With a single endpoint this
+/// degenerates to always choosing it, so sinks that don't configure
+/// multiple endpoints see no change in behavior.
+///
+/// Only the two sampled endpoints are polled on a given `poll_ready` call,
+/// and an endpoint already known to be ready (e.g. the loser of a previous
+/// round's pair) isn't polled again until `call` consumes it — polling
+/// every endpoint on every dispatch would otherwise have every losing
+/// endpoint reserve readiness/capacity it never uses.
+#[derive(Debug)]
+pub struct Balance<S> {
+    endpoints: Vec<S>,
+    ready: Vec<bool>,
+    chosen: Option<usize>,
+}
+
+impl<S> Balance<S> {
+    pub fn new(endpoints: Vec<S>) -> Self {
+        assert!(
+            !endpoints.is_empty(),
+            "Balance requires at least one endpoint"
+        );
+        let ready = vec![false; endpoints.len()];
+        Self {
+            endpoints,
+            ready,
+            chosen: None,
+        }
+    }
+
+    fn sample_pair(&self) -> (usize, usize) {
+        if self.endpoints.len() == 1 {
+            return (0, 0);
+        }
+
+        let mut rng = rand::thread_rng();
+        let first = rng.gen_range(0..self.endpoints.len());
+        let second = loop {
+            let candidate = rng.gen_range(0..self.endpoints.len());
+            if candidate != first {
+                break candidate;
+            }
+        };
+        (first, second)
+    }
+}
+
+impl<S, Request> Service<Request> for Balance<S>
+where
+    S: Service<Request> + Load,
+    Request: Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let (a, b) = self.sample_pair();
+
+        let mut last_error = None;
+        let mut any_pending = false;
+
+        // With a single endpoint `sample_pair` degenerates to `(0, 0)`; poll
+        // it at most once per call instead of twice, which would otherwise
+        // happen whenever the first poll came back `Pending` or `Err` (the
+        // `self.ready[index]` guard below only skips an already-ready slot).
+        let indices: &[usize] = if a == b { &[a] } else { &[a, b] };
+
+        for &index in indices {
+            if self.ready[index] {
+                continue;
+            }
+            match self.endpoints[index].poll_ready(cx) {
+                Poll::Ready(Ok(())) => self.ready[index] = true,
+                Poll::Ready(Err(error)) => last_error = Some(error),
+                Poll::Pending => any_pending = true,
+            }
+        }
+
+        let chosen = match (self.ready[a], self.ready[b]) {
+            (true, true) => {
+                if self.endpoints[a].load() <= self.endpoints[b].load() {
+                    a
+                } else {
+                    b
+                }
+            }
+            (true, false) => a,
+            (false, true) => b,
+            (false, false) => {
+                // Both sampled endpoints are unavailable. If at least one
+                // of them is merely pending, its waker will re-poll us once
+                // it becomes ready (or errors), so parking is correct. But
+                // if neither is pending — e.g. both circuit breakers are
+                // Open and return their error synchronously — no waker is
+                // ever armed, so returning `Pending` here would hang
+                // forever. Surface the error instead so the caller can
+                // observe and retry/back off through its own policy.
+                return match last_error {
+                    Some(error) if !any_pending => Poll::Ready(Err(error)),
+                    _ => Poll::Pending,
+                };
+            }
+        };
+
+        self.chosen = Some(chosen);
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let index = self
+            .chosen
+            .take()
+            .expect("poll_ready must be called before call");
+        self.ready[index] = false;
+        self.endpoints[index].call(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Clone, Copy)]
+    enum MockMode {
+        Ready,
+        Pending,
+        Err,
+    }
+
+    #[derive(Clone)]
+    struct MockEndpoint {
+        mode: MockMode,
+        load: usize,
+        poll_calls: Arc<AtomicUsize>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl MockEndpoint {
+        fn new(mode: MockMode, load: usize) -> Self {
+            Self {
+                mode,
+                load,
+                poll_calls: Arc::new(AtomicUsize::new(0)),
+                calls: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+    }
+
+    impl Service<()> for MockEndpoint {
+        type Response = ();
+        type Error = std::io::Error;
+        type Future = futures::future::Ready<Result<(), std::io::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.poll_calls.fetch_add(1, Ordering::SeqCst);
+            match self.mode {
+                MockMode::Ready => Poll::Ready(Ok(())),
+                MockMode::Pending => Poll::Pending,
+                MockMode::Err => Poll::Ready(Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "endpoint down",
+                ))),
+            }
+        }
+
+        fn call(&mut self, _request: ()) -> Self::Future {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            futures::future::ready(Ok(()))
+        }
+    }
+
+    impl Load for MockEndpoint {
+        fn load(&self) -> usize {
+            self.load
+        }
+    }
+
+    fn noop_context() -> Context<'static> {
+        let waker = futures::task::noop_waker_ref();
+        Context::from_waker(waker)
+    }
+
+    #[test]
+    fn picks_the_lower_load_endpoint() {
+        let busy = MockEndpoint::new(MockMode::Ready, 10);
+        let idle = MockEndpoint::new(MockMode::Ready, 0);
+        let busy_calls = Arc::clone(&busy.calls);
+        let idle_calls = Arc::clone(&idle.calls);
+
+        let mut balance = Balance::new(vec![busy, idle]);
+        let mut cx = noop_context();
+
+        for _ in 0..10 {
+            assert!(matches!(balance.poll_ready(&mut cx), Poll::Ready(Ok(()))));
+            let _ = balance.call(());
+        }
+
+        assert_eq!(busy_calls.load(Ordering::SeqCst), 0);
+        assert_eq!(idle_calls.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn ready_endpoint_is_not_repolled_until_consumed() {
+        let endpoint = MockEndpoint::new(MockMode::Ready, 0);
+        let poll_calls = Arc::clone(&endpoint.poll_calls);
+
+        let mut balance = Balance::new(vec![endpoint]);
+        let mut cx = noop_context();
+
+        assert!(matches!(balance.poll_ready(&mut cx), Poll::Ready(Ok(()))));
+        assert!(matches!(balance.poll_ready(&mut cx), Poll::Ready(Ok(()))));
+        assert_eq!(
+            poll_calls.load(Ordering::SeqCst),
+            1,
+            "a cached-ready endpoint shouldn't be polled again before being consumed"
+        );
+
+        let _ = balance.call(());
+        assert!(matches!(balance.poll_ready(&mut cx), Poll::Ready(Ok(()))));
+        assert_eq!(
+            poll_calls.load(Ordering::SeqCst),
+            2,
+            "readiness should be re-checked only after `call` consumes the previous reservation"
+        );
+    }
+
+    #[test]
+    fn propagates_an_error_when_all_sampled_endpoints_are_erroring_not_pending() {
+        let a = MockEndpoint::new(MockMode::Err, 0);
+        let b = MockEndpoint::new(MockMode::Err, 0);
+
+        let mut balance = Balance::new(vec![a, b]);
+        let mut cx = noop_context();
+
+        assert!(
+            matches!(balance.poll_ready(&mut cx), Poll::Ready(Err(_))),
+            "an all-erroring pair must surface an error rather than hang with no waker armed"
+        );
+    }
+
+    #[test]
+    fn single_endpoint_is_polled_at_most_once_when_not_ready() {
+        let endpoint = MockEndpoint::new(MockMode::Pending, 0);
+        let poll_calls = Arc::clone(&endpoint.poll_calls);
+
+        let mut balance = Balance::new(vec![endpoint]);
+        let mut cx = noop_context();
+
+        assert!(matches!(balance.poll_ready(&mut cx), Poll::Pending));
+        assert_eq!(
+            poll_calls.load(Ordering::SeqCst),
+            1,
+            "the degenerate (0, 0) sample pair must not poll the sole endpoint twice in one call"
+        );
+    }
+
+    #[test]
+    fn a_pending_sibling_takes_priority_over_an_erroring_one() {
+        let err = MockEndpoint::new(MockMode::Err, 0);
+        let pending = MockEndpoint::new(MockMode::Pending, 0);
+
+        let mut balance = Balance::new(vec![err, pending]);
+        let mut cx = noop_context();
+
+        assert!(matches!(balance.poll_ready(&mut cx), Poll::Pending));
+    }
+}
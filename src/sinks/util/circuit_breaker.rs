@@ -0,0 +1,451 @@
+use crate::sinks::util::retries::RetryLogic;
+use crate::sinks::util::sink::Response;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
+
+/// Returned by the breaker instead of calling the inner service while it's
+/// in the `Open` state. This lets a backend that's hard-down fail fast
+/// rather than piling up timeouts and retries against it.
+#[derive(Debug, Default)]
+pub struct CircuitOpenError;
+
+impl fmt::Display for CircuitOpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "circuit breaker is open")
+    }
+}
+
+impl std::error::Error for CircuitOpenError {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitBreakerSettings {
+    /// Whether the breaker is enabled at all. Defaults to `false`; when
+    /// disabled the layer is a transparent pass-through.
+    pub enabled: bool,
+    pub failure_threshold: u32,
+    pub cooldown: Duration,
+    pub half_open_probes: u32,
+}
+
+#[derive(Debug)]
+enum State {
+    Closed { consecutive_failures: u32 },
+    Open { opened_at: Instant },
+    HalfOpen { probes_in_flight: u32 },
+}
+
+#[derive(Debug)]
+struct Inner<RL> {
+    settings: CircuitBreakerSettings,
+    retry_logic: RL,
+    state: Mutex<State>,
+}
+
+impl<RL> Inner<RL> {
+    /// If we're open and the cooldown has elapsed, move to half-open and
+    /// allow a limited number of trial requests through.
+    fn maybe_recover(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let State::Open { opened_at } = *state {
+            if opened_at.elapsed() >= self.settings.cooldown {
+                *state = State::HalfOpen {
+                    probes_in_flight: 0,
+                };
+            }
+        }
+    }
+
+    /// Returns `true` if the breaker is currently refusing calls, reserving
+    /// a half-open probe slot as a side effect when one is available. If the
+    /// caller ends up not being able to use an admitted probe slot (the
+    /// wrapped service wasn't actually ready), it must call
+    /// `release_half_open_probe` so the slot isn't leaked forever.
+    fn is_blocking(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match &mut *state {
+            State::Closed { .. } => false,
+            State::Open { .. } => true,
+            State::HalfOpen { probes_in_flight } => {
+                if *probes_in_flight < self.settings.half_open_probes {
+                    *probes_in_flight += 1;
+                    false
+                } else {
+                    true
+                }
+            }
+        }
+    }
+
+    /// Gives back a half-open probe slot reserved by `is_blocking` that went
+    /// unused because the wrapped service didn't actually become ready (or
+    /// errored) in response to the probe. A no-op if the breaker has since
+    /// left `HalfOpen`.
+    fn release_half_open_probe(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let State::HalfOpen { probes_in_flight } = &mut *state {
+            *probes_in_flight = probes_in_flight.saturating_sub(1);
+        }
+    }
+
+    fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        match &mut *state {
+            State::Closed {
+                consecutive_failures,
+            } => {
+                *consecutive_failures = 0;
+            }
+            State::HalfOpen { .. } => {
+                *state = State::Closed {
+                    consecutive_failures: 0,
+                };
+            }
+            // A success racing a just-opened breaker (e.g. a slower request
+            // that was already in flight under Closed) must not undo the
+            // trip; only the cooldown elapsing moves us out of Open.
+            State::Open { .. } => {}
+        }
+    }
+
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        match &mut *state {
+            State::Closed {
+                consecutive_failures,
+            } => {
+                *consecutive_failures += 1;
+                if *consecutive_failures >= self.settings.failure_threshold {
+                    warn!(
+                        message = "Circuit breaker tripped; opening.",
+                        failures = *consecutive_failures
+                    );
+                    *state = State::Open {
+                        opened_at: Instant::now(),
+                    };
+                }
+            }
+            State::HalfOpen { .. } => {
+                warn!(message = "Half-open probe failed; re-opening circuit breaker.");
+                *state = State::Open {
+                    opened_at: Instant::now(),
+                };
+            }
+            State::Open { .. } => {}
+        }
+    }
+}
+
+/// A three-state (closed/open/half-open) circuit breaker `Layer`, placed
+/// just outside the timeout/retry layers so a hard-down backend is detected
+/// once and then short-circuited, instead of every request separately
+/// paying for a timeout and its retries. Disabled by default; opt in via
+/// `TowerRequestConfig`'s `circuit_breaker_*` fields.
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerLayer<RL> {
+    settings: CircuitBreakerSettings,
+    retry_logic: RL,
+}
+
+impl<RL> CircuitBreakerLayer<RL> {
+    pub fn new(settings: CircuitBreakerSettings, retry_logic: RL) -> Self {
+        Self {
+            settings,
+            retry_logic,
+        }
+    }
+}
+
+impl<S, RL> Layer<S> for CircuitBreakerLayer<RL>
+where
+    RL: Clone,
+{
+    type Service = CircuitBreaker<S, RL>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CircuitBreaker {
+            inner,
+            state: Arc::new(Inner {
+                settings: self.settings,
+                retry_logic: self.retry_logic.clone(),
+                state: Mutex::new(State::Closed {
+                    consecutive_failures: 0,
+                }),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker<S, RL> {
+    inner: S,
+    state: Arc<Inner<RL>>,
+}
+
+impl<S, RL> CircuitBreaker<S, RL> {
+    /// Gets a reference to the wrapped service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S, RL, Request> Service<Request> for CircuitBreaker<S, RL>
+where
+    S: Service<Request>,
+    S::Error: Into<crate::Error> + Send + Sync + 'static,
+    S::Response: Response,
+    S::Future: Send + 'static,
+    RL: RetryLogic<Response = S::Response>,
+{
+    type Response = S::Response;
+    type Error = crate::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if !self.state.settings.enabled {
+            return self.inner.poll_ready(cx).map_err(Into::into);
+        }
+
+        self.state.maybe_recover();
+
+        if self.state.is_blocking() {
+            return Poll::Ready(Err(CircuitOpenError.into()));
+        }
+
+        // `is_blocking` may have just reserved a half-open probe slot. If the
+        // wrapped service isn't actually ready yet (or errors), no `call`
+        // will ever follow to resolve that probe via `record_success`/
+        // `record_failure`, so give the slot back here. Otherwise a single
+        // transient `Pending` from the inner stack during a probe would wedge
+        // the breaker in `HalfOpen` forever.
+        match self.inner.poll_ready(cx) {
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(())),
+            Poll::Ready(Err(error)) => {
+                self.state.release_half_open_probe();
+                Poll::Ready(Err(error.into()))
+            }
+            Poll::Pending => {
+                self.state.release_half_open_probe();
+                Poll::Pending
+            }
+        }
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let future = self.inner.call(request);
+        let state = Arc::clone(&self.state);
+
+        async move {
+            if !state.settings.enabled {
+                return future.await.map_err(Into::into);
+            }
+
+            match future.await {
+                Ok(response) => {
+                    if response.is_successful() {
+                        state.record_success();
+                    } else {
+                        state.record_failure();
+                    }
+                    Ok(response)
+                }
+                Err(error) => {
+                    let error = error.into();
+                    let is_failure = match error.downcast_ref::<RL::Error>() {
+                        Some(expected) => state.retry_logic.is_retriable_error(expected),
+                        None => true,
+                    };
+                    if is_failure {
+                        state.record_failure();
+                    } else {
+                        state.record_success();
+                    }
+                    Err(error)
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct TestLogic;
+
+    impl RetryLogic for TestLogic {
+        type Error = std::io::Error;
+        type Response = MockResponse;
+
+        fn is_retriable_error(&self, _error: &Self::Error) -> bool {
+            true
+        }
+    }
+
+    struct MockResponse;
+
+    impl Response for MockResponse {
+        fn is_successful(&self) -> bool {
+            true
+        }
+    }
+
+    /// A wrapped service whose `poll_ready` replays a fixed, queued sequence
+    /// of outcomes (defaulting to `Ready` once the queue is drained), so a
+    /// test can simulate the wrapped stack being momentarily saturated during
+    /// a half-open probe.
+    #[derive(Clone)]
+    struct MockInner {
+        poll_outcomes: Arc<Mutex<std::collections::VecDeque<Poll<()>>>>,
+    }
+
+    impl MockInner {
+        fn new(poll_outcomes: Vec<Poll<()>>) -> Self {
+            Self {
+                poll_outcomes: Arc::new(Mutex::new(poll_outcomes.into())),
+            }
+        }
+    }
+
+    impl tower::Service<()> for MockInner {
+        type Response = MockResponse;
+        type Error = std::io::Error;
+        type Future = futures::future::Ready<Result<MockResponse, std::io::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            match self.poll_outcomes.lock().unwrap().pop_front() {
+                Some(Poll::Ready(())) | None => Poll::Ready(Ok(())),
+                Some(Poll::Pending) => Poll::Pending,
+            }
+        }
+
+        fn call(&mut self, _request: ()) -> Self::Future {
+            futures::future::ready(Ok(MockResponse))
+        }
+    }
+
+    fn test_inner(failure_threshold: u32) -> Inner<TestLogic> {
+        Inner {
+            settings: CircuitBreakerSettings {
+                enabled: true,
+                failure_threshold,
+                cooldown: Duration::from_millis(10),
+                half_open_probes: 1,
+            },
+            retry_logic: TestLogic,
+            state: Mutex::new(State::Closed {
+                consecutive_failures: 0,
+            }),
+        }
+    }
+
+    #[test]
+    fn trips_open_after_threshold_failures() {
+        let inner = test_inner(2);
+        inner.record_failure();
+        assert!(!inner.is_blocking());
+        inner.record_failure();
+        assert!(inner.is_blocking());
+    }
+
+    #[test]
+    fn success_resets_failure_count_while_closed() {
+        let inner = test_inner(2);
+        inner.record_failure();
+        inner.record_success();
+        inner.record_failure();
+        assert!(
+            !inner.is_blocking(),
+            "a single failure after a reset shouldn't trip the breaker"
+        );
+    }
+
+    #[test]
+    fn success_does_not_clobber_open_state() {
+        let inner = test_inner(1);
+        inner.record_failure(); // trips open
+        inner.record_success(); // a racing success must not undo the trip
+        assert!(
+            inner.is_blocking(),
+            "an in-flight success must not override an open breaker"
+        );
+    }
+
+    #[test]
+    fn recovers_to_half_open_after_cooldown_and_reopens_on_failed_probe() {
+        let inner = test_inner(1);
+        inner.record_failure(); // open
+        std::thread::sleep(Duration::from_millis(20));
+        inner.maybe_recover();
+        assert!(
+            !inner.is_blocking(),
+            "a probe should be admitted once half-open"
+        );
+        inner.record_failure();
+        assert!(
+            inner.is_blocking(),
+            "a failed probe should re-open the breaker"
+        );
+    }
+
+    #[test]
+    fn half_open_admits_only_configured_probe_count() {
+        let inner = Inner {
+            settings: CircuitBreakerSettings {
+                enabled: true,
+                failure_threshold: 1,
+                cooldown: Duration::from_millis(10),
+                half_open_probes: 1,
+            },
+            retry_logic: TestLogic,
+            state: Mutex::new(State::HalfOpen {
+                probes_in_flight: 0,
+            }),
+        };
+
+        assert!(!inner.is_blocking(), "the first probe should be admitted");
+        assert!(
+            inner.is_blocking(),
+            "a second concurrent probe should be refused"
+        );
+    }
+
+    #[test]
+    fn half_open_probe_is_released_when_inner_is_not_ready() {
+        // Exercises the real `Service::poll_ready` path (not `Inner` methods
+        // directly): a half-open probe whose inner poll comes back `Pending`
+        // must give its slot back, rather than leaving the breaker wedged in
+        // `HalfOpen` forever.
+        let mock = MockInner::new(vec![Poll::Pending, Poll::Ready(())]);
+        let state = Arc::new(Inner {
+            settings: CircuitBreakerSettings {
+                enabled: true,
+                failure_threshold: 1,
+                cooldown: Duration::from_millis(10),
+                half_open_probes: 1,
+            },
+            retry_logic: TestLogic,
+            state: Mutex::new(State::HalfOpen {
+                probes_in_flight: 0,
+            }),
+        });
+        let mut breaker = CircuitBreaker { inner: mock, state };
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(
+            matches!(breaker.poll_ready(&mut cx), Poll::Pending),
+            "the inner stack reporting Pending should propagate as Pending, not CircuitOpenError"
+        );
+        assert!(
+            matches!(breaker.poll_ready(&mut cx), Poll::Ready(Ok(()))),
+            "the released probe slot should be available to a later attempt"
+        );
+    }
+}
@@ -0,0 +1,392 @@
+use crate::Error;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::time::Duration;
+use tower::retry::Policy;
+
+/// How retry delays are spread out to avoid every in-flight request backing
+/// off in lockstep and retrying in synchronized waves against a backend
+/// that's already struggling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryJitterMode {
+    /// Always wait exactly the computed backoff, as before.
+    None,
+    /// Wait a uniformly random duration in `[0, computed backoff]`.
+    Full,
+}
+
+impl Default for RetryJitterMode {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+/// Applies a jitter strategy to a computed backoff, returning the actual
+/// duration to sleep for.
+fn jittered_delay(jitter: RetryJitterMode, base: Duration) -> Duration {
+    match jitter {
+        RetryJitterMode::None => base,
+        RetryJitterMode::Full => {
+            if base.is_zero() {
+                base
+            } else {
+                Duration::from_secs_f64(rand::thread_rng().gen_range(0.0..base.as_secs_f64()))
+            }
+        }
+    }
+}
+
+pub enum RetryAction {
+    /// Indicate that this request should be retried with a reason
+    Retry(String),
+    /// Indicate that this request should not be retried with a reason
+    DontRetry(String),
+    /// Indicate that this request was successfully processed
+    Successful,
+}
+
+pub trait RetryLogic: Clone + Send + Sync + 'static {
+    type Error: std::error::Error + Send + Sync + 'static;
+    type Response;
+
+    fn is_retriable_error(&self, error: &Self::Error) -> bool;
+
+    fn should_retry_response(&self, _response: &Self::Response) -> RetryAction {
+        RetryAction::Successful
+    }
+}
+
+/// A shared, self-healing budget for retries that's drawn down by every
+/// clone of a `FixedRetryPolicy` going through the same service.
+///
+/// This follows the "standard token bucket" shape used by the AWS SDKs: the
+/// bucket starts full, each retry attempt costs a fixed number of tokens, and
+/// a request that completes without needing the full retry budget pays some
+/// of it back. Once the bucket runs dry, retries are refused outright even if
+/// `retry_attempts` hasn't been exhausted yet, which caps the amount of extra
+/// load a struggling backend can be hit with.
+#[derive(Debug, Clone)]
+pub struct RetryTokenBucket {
+    available: Arc<AtomicUsize>,
+    capacity: usize,
+    retry_cost: usize,
+    success_reward: usize,
+}
+
+impl RetryTokenBucket {
+    pub fn new(capacity: usize, retry_cost: usize, success_reward: usize) -> Self {
+        Self {
+            available: Arc::new(AtomicUsize::new(capacity)),
+            capacity,
+            retry_cost,
+            success_reward,
+        }
+    }
+
+    /// Attempt to withdraw the cost of a single retry from the bucket.
+    /// Returns `false`, without changing the bucket, if there aren't enough
+    /// tokens available.
+    fn try_withdraw(&self) -> bool {
+        let mut available = self.available.load(Ordering::Relaxed);
+        loop {
+            if available < self.retry_cost {
+                return false;
+            }
+            let new_available = available - self.retry_cost;
+            match self.available.compare_exchange_weak(
+                available,
+                new_available,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(current) => available = current,
+            }
+        }
+    }
+
+    fn deposit(&self, amount: usize) {
+        let mut available = self.available.load(Ordering::Relaxed);
+        loop {
+            let new_available = self.capacity.min(available + amount);
+            match self.available.compare_exchange_weak(
+                available,
+                new_available,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(current) => available = current,
+            }
+        }
+    }
+
+    /// Return the cost of a retry to the bucket, as a reward for a request
+    /// that eventually succeeded after retrying.
+    fn reward_retry(&self) {
+        self.deposit(self.retry_cost);
+    }
+
+    /// Return a small reward to the bucket for a request that succeeded
+    /// without needing to retry at all.
+    fn reward_success(&self) {
+        self.deposit(self.success_reward);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FixedRetryPolicy<L> {
+    remaining_attempts: usize,
+    retried: bool,
+    initial_backoff: Duration,
+    max_duration: Duration,
+    current_duration: Duration,
+    token_bucket: Option<RetryTokenBucket>,
+    jitter: RetryJitterMode,
+    logic: L,
+}
+
+impl<L: RetryLogic> FixedRetryPolicy<L> {
+    pub fn new(
+        remaining_attempts: usize,
+        initial_backoff: Duration,
+        max_duration: Duration,
+        logic: L,
+    ) -> Self {
+        FixedRetryPolicy {
+            remaining_attempts,
+            retried: false,
+            initial_backoff,
+            max_duration,
+            current_duration: Duration::from_secs(0),
+            token_bucket: None,
+            jitter: RetryJitterMode::None,
+            logic,
+        }
+    }
+
+    pub fn with_token_bucket(mut self, token_bucket: RetryTokenBucket) -> Self {
+        self.token_bucket = Some(token_bucket);
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: RetryJitterMode) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    fn advance(&self) -> Self {
+        let new_duration = self
+            .current_duration
+            .checked_mul(2)
+            .unwrap_or(self.max_duration)
+            .max(self.initial_backoff)
+            .min(self.max_duration);
+
+        FixedRetryPolicy {
+            remaining_attempts: self.remaining_attempts - 1,
+            retried: true,
+            current_duration: new_duration,
+            ..self.clone()
+        }
+    }
+
+    /// Advance to the next attempt and sleep before the retried request is
+    /// issued. The per-attempt backoff stored in `next.current_duration`
+    /// (doubled and clamped to `max_duration` by `advance`) is unaffected by
+    /// jitter; only the actual sleep is randomized. The total time spent
+    /// across all retries of a request is not tracked or bounded here.
+    fn delayed_retry(&self) -> BoxFuture<'static, Self> {
+        let next = self.advance();
+        let delay = jittered_delay(self.jitter, next.backoff());
+        async move {
+            tokio::time::sleep(delay).await;
+            next
+        }
+        .boxed()
+    }
+
+    fn backoff(&self) -> Duration {
+        self.current_duration
+    }
+
+    /// Consult the shared token bucket (if any) about whether this attempt
+    /// can afford to retry. A policy with no bucket configured always allows
+    /// the retry, preserving the pre-token-bucket behavior.
+    fn has_budget_for_retry(&self) -> bool {
+        match &self.token_bucket {
+            Some(bucket) => bucket.try_withdraw(),
+            None => true,
+        }
+    }
+
+    fn reward_outcome(&self) {
+        if let Some(bucket) = &self.token_bucket {
+            if self.retried {
+                bucket.reward_retry();
+            } else {
+                bucket.reward_success();
+            }
+        }
+    }
+}
+
+impl<Req, Res, L> Policy<Req, Res, Error> for FixedRetryPolicy<L>
+where
+    Req: Clone,
+    L: RetryLogic<Response = Res>,
+{
+    type Future = BoxFuture<'static, Self>;
+
+    fn retry(&self, _: &Req, result: Result<&Res, &Error>) -> Option<Self::Future> {
+        match result {
+            Ok(response) => match self.logic.should_retry_response(response) {
+                RetryAction::Retry(reason) => {
+                    if self.remaining_attempts == 0 {
+                        error!(message = "Retries exhausted.", %reason);
+                        return None;
+                    }
+                    if !self.has_budget_for_retry() {
+                        warn!(message = "Retry budget exhausted; not retrying.", %reason);
+                        return None;
+                    }
+
+                    warn!(message = "Retrying after response.", %reason);
+                    Some(self.delayed_retry())
+                }
+                RetryAction::DontRetry(reason) => {
+                    if !reason.is_empty() {
+                        warn!(message = "Not retriable.", reason = ?reason);
+                    }
+                    None
+                }
+                RetryAction::Successful => {
+                    self.reward_outcome();
+                    None
+                }
+            },
+            Err(error) => {
+                if let Some(expected) = error.downcast_ref::<L::Error>() {
+                    if self.logic.is_retriable_error(expected) {
+                        if self.remaining_attempts == 0 {
+                            error!(message = "Retries exhausted.", %expected);
+                            return None;
+                        }
+                        if !self.has_budget_for_retry() {
+                            warn!(message = "Retry budget exhausted; not retrying.", %expected);
+                            return None;
+                        }
+
+                        warn!(message = "Retrying after error.", %expected);
+                        Some(self.delayed_retry())
+                    } else {
+                        None
+                    }
+                } else {
+                    warn!(message = "Unexpected error type; not retrying.");
+                    None
+                }
+            }
+        }
+    }
+
+    fn clone_request(&self, request: &Req) -> Option<Req> {
+        Some(request.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct TestLogic;
+
+    impl RetryLogic for TestLogic {
+        type Error = std::io::Error;
+        type Response = ();
+
+        fn is_retriable_error(&self, _error: &Self::Error) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn token_bucket_enforces_budget() {
+        let bucket = RetryTokenBucket::new(10, 5, 1);
+        assert!(bucket.try_withdraw());
+        assert!(bucket.try_withdraw());
+        assert!(!bucket.try_withdraw(), "bucket should be empty");
+    }
+
+    #[test]
+    fn token_bucket_rewards_are_clamped_to_capacity() {
+        let bucket = RetryTokenBucket::new(10, 5, 1);
+        for _ in 0..20 {
+            bucket.reward_success();
+        }
+
+        assert!(bucket.try_withdraw());
+        assert!(bucket.try_withdraw());
+        assert!(
+            !bucket.try_withdraw(),
+            "rewards shouldn't have pushed the bucket past its capacity"
+        );
+    }
+
+    #[test]
+    fn advance_doubles_and_clamps_backoff() {
+        let policy = FixedRetryPolicy::new(
+            5,
+            Duration::from_secs(1),
+            Duration::from_secs(4),
+            TestLogic,
+        );
+
+        let first = policy.advance();
+        assert_eq!(first.backoff(), Duration::from_secs(1));
+
+        let second = first.advance();
+        assert_eq!(second.backoff(), Duration::from_secs(2));
+
+        let third = second.advance();
+        assert_eq!(third.backoff(), Duration::from_secs(4));
+
+        let fourth = third.advance();
+        assert_eq!(
+            fourth.backoff(),
+            Duration::from_secs(4),
+            "backoff must not exceed retry_max_duration_secs"
+        );
+    }
+
+    #[test]
+    fn jitter_none_is_deterministic() {
+        let base = Duration::from_secs(4);
+        assert_eq!(jittered_delay(RetryJitterMode::None, base), base);
+    }
+
+    #[test]
+    fn jitter_full_never_exceeds_base() {
+        let base = Duration::from_secs(4);
+        for _ in 0..100 {
+            let delay = jittered_delay(RetryJitterMode::Full, base);
+            assert!(delay <= base);
+        }
+    }
+
+    #[test]
+    fn jitter_full_handles_zero_base() {
+        assert_eq!(
+            jittered_delay(RetryJitterMode::Full, Duration::from_secs(0)),
+            Duration::from_secs(0)
+        );
+    }
+}
@@ -2,7 +2,11 @@ use crate::buffers::Acker;
 use crate::sinks::util::adaptive_concurrency::{
     AdaptiveConcurrencyLimit, AdaptiveConcurrencyLimitLayer, AdaptiveConcurrencySettings,
 };
-use crate::sinks::util::retries::{FixedRetryPolicy, RetryLogic};
+use crate::sinks::util::balance::Balance;
+use crate::sinks::util::circuit_breaker::{
+    CircuitBreaker, CircuitBreakerLayer, CircuitBreakerSettings,
+};
+use crate::sinks::util::retries::{FixedRetryPolicy, RetryJitterMode, RetryLogic, RetryTokenBucket};
 pub use crate::sinks::util::service::concurrency::{Concurrency, ConcurrencyOption};
 pub use crate::sinks::util::service::map::Map;
 use crate::sinks::util::service::map::MapLayer;
@@ -22,9 +26,12 @@ use tower::{
 mod concurrency;
 mod map;
 
-pub type Svc<S, L> = RateLimit<Retry<FixedRetryPolicy<L>, AdaptiveConcurrencyLimit<Timeout<S>, L>>>;
+pub type Svc<S, L> = RateLimit<
+    CircuitBreaker<Retry<FixedRetryPolicy<L>, AdaptiveConcurrencyLimit<Timeout<S>, L>>, L>,
+>;
 pub type TowerBatchedSink<S, B, RL, SL> = BatchSink<Svc<S, RL>, B, SL>;
 pub type TowerPartitionSink<S, B, RL, K, SL> = PartitionBatchSink<Svc<S, RL>, B, K, SL>;
+pub type BalancedSvc<S, L> = Balance<Svc<S, L>>;
 
 pub trait ServiceBuilderExt<L> {
     fn map<R1, R2, F>(self, f: F) -> ServiceBuilder<Stack<MapLayer<R1, R2>, L>>
@@ -76,6 +83,26 @@ pub struct TowerRequestConfig<T: ConcurrencyOption = Concurrency> {
     pub retry_attempts: Option<usize>,         // isize::MAX
     pub retry_max_duration_secs: Option<u64>,
     pub retry_initial_backoff_secs: Option<u64>, // 1
+    /// The size of the shared retry token bucket (e.g. 500). Unset (the
+    /// default) disables the bucket entirely, preserving today's
+    /// per-request retry behavior.
+    pub retry_token_bucket_size: Option<usize>,
+    /// The number of tokens a single retry attempt draws from the bucket.
+    pub retry_cost: Option<usize>, // 5
+    /// The number of tokens returned to the bucket by a request that
+    /// succeeds without needing to retry.
+    pub retry_success_reward: Option<usize>, // 1
+    /// The number of consecutive failures that trips the circuit breaker
+    /// open. Unset (the default) disables the breaker entirely.
+    pub circuit_breaker_failure_threshold: Option<u32>,
+    /// How long the breaker stays open before allowing half-open probes.
+    pub circuit_breaker_cooldown_secs: Option<u64>, // 30 seconds
+    /// The number of trial requests admitted while half-open.
+    pub circuit_breaker_half_open_probes: Option<u32>, // 1
+    /// How retry delays are randomized: "none" keeps today's deterministic
+    /// backoff, "full" spreads retries out to avoid thundering herds.
+    #[serde(default)]
+    pub retry_jitter: RetryJitterMode,
     #[serde(default)]
     pub adaptive_concurrency: AdaptiveConcurrencySettings,
 }
@@ -85,6 +112,10 @@ pub const RATE_LIMIT_NUM_DEFAULT: u64 = i64::max_value() as u64; // i64 avoids T
 pub const RETRY_ATTEMPTS_DEFAULT: usize = isize::max_value() as usize; // isize avoids TOML deserialize issue
 pub const RETRY_MAX_DURATION_SECONDS_DEFAULT: u64 = 3_600; // one hour
 pub const RETRY_INITIAL_BACKOFF_SECONDS_DEFAULT: u64 = 1; // one second
+pub const RETRY_COST_DEFAULT: usize = 5;
+pub const RETRY_SUCCESS_REWARD_DEFAULT: usize = 1;
+pub const CIRCUIT_BREAKER_COOLDOWN_SECONDS_DEFAULT: u64 = 30; // thirty seconds
+pub const CIRCUIT_BREAKER_HALF_OPEN_PROBES_DEFAULT: u32 = 1;
 pub const TIMEOUT_SECONDS_DEFAULT: u64 = 60; // one minute
 
 impl<T> Default for TowerRequestConfig<T>
@@ -101,6 +132,13 @@ where
             retry_attempts: Some(RETRY_ATTEMPTS_DEFAULT),
             retry_max_duration_secs: Some(RETRY_MAX_DURATION_SECONDS_DEFAULT),
             retry_initial_backoff_secs: Some(RETRY_INITIAL_BACKOFF_SECONDS_DEFAULT),
+            retry_token_bucket_size: None,
+            retry_cost: None,
+            retry_success_reward: None,
+            circuit_breaker_failure_threshold: None,
+            circuit_breaker_cooldown_secs: None,
+            circuit_breaker_half_open_probes: None,
+            retry_jitter: RetryJitterMode::None,
             adaptive_concurrency: AdaptiveConcurrencySettings::default(),
         }
     }
@@ -138,6 +176,40 @@ impl<T: ConcurrencyOption> TowerRequestConfig<T> {
                     .or(defaults.retry_initial_backoff_secs)
                     .unwrap_or(RETRY_INITIAL_BACKOFF_SECONDS_DEFAULT),
             ),
+            retry_token_bucket: self
+                .retry_token_bucket_size
+                .or(defaults.retry_token_bucket_size)
+                .map(|size| {
+                    RetryTokenBucket::new(
+                        size,
+                        self.retry_cost
+                            .or(defaults.retry_cost)
+                            .unwrap_or(RETRY_COST_DEFAULT),
+                        self.retry_success_reward
+                            .or(defaults.retry_success_reward)
+                            .unwrap_or(RETRY_SUCCESS_REWARD_DEFAULT),
+                    )
+                }),
+            retry_jitter: self.retry_jitter,
+            circuit_breaker: CircuitBreakerSettings {
+                enabled: self
+                    .circuit_breaker_failure_threshold
+                    .or(defaults.circuit_breaker_failure_threshold)
+                    .is_some(),
+                failure_threshold: self
+                    .circuit_breaker_failure_threshold
+                    .or(defaults.circuit_breaker_failure_threshold)
+                    .unwrap_or(u32::max_value()),
+                cooldown: Duration::from_secs(
+                    self.circuit_breaker_cooldown_secs
+                        .or(defaults.circuit_breaker_cooldown_secs)
+                        .unwrap_or(CIRCUIT_BREAKER_COOLDOWN_SECONDS_DEFAULT),
+                ),
+                half_open_probes: self
+                    .circuit_breaker_half_open_probes
+                    .or(defaults.circuit_breaker_half_open_probes)
+                    .unwrap_or(CIRCUIT_BREAKER_HALF_OPEN_PROBES_DEFAULT),
+            },
             adaptive_concurrency: self.adaptive_concurrency,
         }
     }
@@ -163,17 +235,25 @@ pub struct TowerRequestSettings {
     pub retry_attempts: usize,
     pub retry_max_duration_secs: Duration,
     pub retry_initial_backoff_secs: Duration,
+    pub retry_token_bucket: Option<RetryTokenBucket>,
+    pub retry_jitter: RetryJitterMode,
+    pub circuit_breaker: CircuitBreakerSettings,
     pub adaptive_concurrency: AdaptiveConcurrencySettings,
 }
 
 impl TowerRequestSettings {
     pub fn retry_policy<L: RetryLogic>(&self, logic: L) -> FixedRetryPolicy<L> {
-        FixedRetryPolicy::new(
+        let policy = FixedRetryPolicy::new(
             self.retry_attempts,
             self.retry_initial_backoff_secs,
             self.retry_max_duration_secs,
             logic,
-        )
+        );
+        let policy = match &self.retry_token_bucket {
+            Some(bucket) => policy.with_token_bucket(bucket.clone()),
+            None => policy,
+        };
+        policy.with_jitter(self.retry_jitter)
     }
 
     pub fn partition_sink<B, RL, S, K, SL>(
@@ -246,6 +326,10 @@ impl TowerRequestSettings {
         let policy = self.retry_policy(retry_logic.clone());
         ServiceBuilder::new()
             .rate_limit(self.rate_limit_num, self.rate_limit_duration)
+            .layer(CircuitBreakerLayer::new(
+                self.circuit_breaker,
+                retry_logic.clone(),
+            ))
             .retry(policy)
             .layer(AdaptiveConcurrencyLimitLayer::new(
                 self.concurrency,
@@ -255,6 +339,33 @@ impl TowerRequestSettings {
             .timeout(self.timeout)
             .service(service)
     }
+
+    /// Like `service`, but spreads requests across several endpoints using
+    /// power-of-two-choices load balancing rather than targeting a single
+    /// backend. Each endpoint gets its own copy of the full adaptive
+    /// concurrency/retry/rate-limit/timeout stack, so a single overloaded or
+    /// failing endpoint is isolated from the others. A single-element
+    /// `endpoints` is equivalent to `service`.
+    pub fn balanced_service<RL, S, Request>(
+        &self,
+        retry_logic: RL,
+        endpoints: Vec<S>,
+    ) -> BalancedSvc<S, RL>
+    where
+        RL: RetryLogic<Response = S::Response>,
+        S: Service<Request> + Clone + Send + 'static,
+        S::Error: Into<crate::Error> + Send + Sync + 'static,
+        S::Response: Send + Response,
+        S::Future: Send + 'static,
+        Request: Send + Clone + 'static,
+    {
+        let services = endpoints
+            .into_iter()
+            .map(|endpoint| self.service(retry_logic.clone(), endpoint))
+            .collect();
+
+        Balance::new(services)
+    }
 }
 
 #[derive(Debug, Clone)]